@@ -0,0 +1,134 @@
+//! Reading the extents of existing schematics.
+
+use std::{fmt::Display, io::{Error, ErrorKind, Read}};
+
+use nbt::{CompoundTag, decode::read_gzip_compound_tag};
+
+use crate::BlockPos;
+
+fn invalid_data<E: Display>(error: E) -> Error {
+    Error::new(ErrorKind::InvalidData, error.to_string())
+}
+
+fn read_pos(tag: &CompoundTag) -> Result<BlockPos, Error> {
+    Ok(BlockPos::new(
+        tag.get_i32("x").map_err(invalid_data)?,
+        tag.get_i32("y").map_err(invalid_data)?,
+        tag.get_i32("z").map_err(invalid_data)?
+    ))
+}
+
+/// Reads the inclusive bounds of every region in a Litematica schematic,
+/// relative to the schematic origin.
+pub fn read_litematic_bounds<R: Read>(reader: &mut R) -> Result<Vec<(BlockPos, BlockPos)>, Error> {
+    let schematic = read_gzip_compound_tag(reader).map_err(invalid_data)?;
+    let regions = schematic.get_compound_tag("Regions").map_err(invalid_data)?;
+
+    let mut bounds = Vec::new();
+    for (name, _) in regions.iter() {
+        let region = regions.get_compound_tag(name).map_err(invalid_data)?;
+        let position = read_pos(region.get_compound_tag("Position").map_err(invalid_data)?)?;
+        let size = read_pos(region.get_compound_tag("Size").map_err(invalid_data)?)?;
+
+        // Sizes may be negative, extending the region from its position in negative direction
+        let corner = |pos: i32, size: i32| if size < 0 { pos + size + 1 } else { pos + size - 1 };
+        let end = BlockPos::new(
+            corner(position.x, size.x),
+            corner(position.y, size.y),
+            corner(position.z, size.z)
+        );
+        bounds.push((position.min(end), position.max(end)));
+    }
+
+    Ok(bounds)
+}
+
+/// Reads the inclusive bounds of a Sponge (`.schem`) schematic in world coordinates,
+/// as stored in its offset by WorldEdit.
+pub fn read_sponge_bounds<R: Read>(reader: &mut R) -> Result<(BlockPos, BlockPos), Error> {
+    let root = read_gzip_compound_tag(reader).map_err(invalid_data)?;
+    // Version 3 nests everything in a `Schematic` tag
+    let schematic = root.get_compound_tag("Schematic").unwrap_or(&root);
+
+    let size = BlockPos::new(
+        schematic.get_i16("Width").map_err(invalid_data)? as u16 as i32,
+        schematic.get_i16("Height").map_err(invalid_data)? as u16 as i32,
+        schematic.get_i16("Length").map_err(invalid_data)? as u16 as i32
+    );
+    let offset = match schematic.get_i32_vec("Offset") {
+        Ok(offset) if offset.len() == 3 => BlockPos::new(offset[0], offset[1], offset[2]),
+        _ => BlockPos::zero()
+    };
+
+    Ok((offset, offset + size - BlockPos::one()))
+}
+
+#[cfg(test)]
+mod tests {
+    use nbt::encode::write_gzip_compound_tag;
+
+    use super::*;
+
+    fn pos_tag(x: i32, y: i32, z: i32) -> CompoundTag {
+        let mut tag = CompoundTag::new();
+        tag.insert_i32("x", x);
+        tag.insert_i32("y", y);
+        tag.insert_i32("z", z);
+        tag
+    }
+
+    fn litematic(position: CompoundTag, size: CompoundTag) -> Vec<u8> {
+        let mut region = CompoundTag::new();
+        region.insert_compound_tag("Position", position);
+        region.insert_compound_tag("Size", size);
+        let mut regions = CompoundTag::new();
+        regions.insert_compound_tag("region", region);
+        let mut schematic = CompoundTag::new();
+        schematic.insert_compound_tag("Regions", regions);
+        write(&schematic)
+    }
+
+    #[test]
+    fn litematic_positive_size() {
+        let file = litematic(pos_tag(10, 0, -5), pos_tag(3, 2, 4));
+        let bounds = read_litematic_bounds(&mut file.as_slice()).unwrap();
+        assert_eq!(bounds, vec![(BlockPos::new(10, 0, -5), BlockPos::new(12, 1, -2))]);
+    }
+
+    #[test]
+    fn litematic_negative_size() {
+        let file = litematic(pos_tag(10, 0, -5), pos_tag(-3, 2, -4));
+        let bounds = read_litematic_bounds(&mut file.as_slice()).unwrap();
+        assert_eq!(bounds, vec![(BlockPos::new(8, 0, -8), BlockPos::new(10, 1, -5))]);
+    }
+
+    fn sponge(width: i16, offset: Option<Vec<i32>>) -> CompoundTag {
+        let mut schematic = CompoundTag::new();
+        schematic.insert_i16("Width", width);
+        schematic.insert_i16("Height", 1);
+        schematic.insert_i16("Length", 2);
+        if let Some(offset) = offset {
+            schematic.insert_i32_vec("Offset", offset);
+        }
+        schematic
+    }
+
+    fn write(tag: &CompoundTag) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        write_gzip_compound_tag(&mut buffer, tag).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn sponge_versions() {
+        // Sizes above i16::MAX are stored as negative shorts
+        let file = write(&sponge(-32768, Some(vec![-16, 64, 32])));
+        let bounds = read_sponge_bounds(&mut file.as_slice()).unwrap();
+        assert_eq!(bounds, (BlockPos::new(-16, 64, 32), BlockPos::new(32751, 64, 33)));
+
+        let mut root = CompoundTag::new();
+        root.insert_compound_tag("Schematic", sponge(4, None));
+        let bounds = read_sponge_bounds(&mut write(&root).as_slice()).unwrap();
+        assert_eq!(bounds, (BlockPos::zero(), BlockPos::new(3, 0, 1)));
+    }
+}
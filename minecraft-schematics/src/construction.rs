@@ -0,0 +1,278 @@
+//! Export to the `.construction` format of the Amulet editor.
+//!
+//! A construction file starts and ends with a magic number. In between are the gzipped
+//! NBT sections of at most 16x16x16 blocks, followed by gzipped NBT metadata
+//! containing the global block palette and an index of the sections.
+
+use std::{collections::HashMap, io::{Error, Write}};
+
+use nbt::{CompoundTag, Tag, encode::write_gzip_compound_tag};
+
+use crate::{BlockPos, BlockState, Schematic};
+
+const MAGIC_NUMBER: &[u8; 8] = b"constrct";
+const FORMAT_VERSION: u8 = 0;
+const SECTION_VERSION: i8 = 0;
+const SECTION_SIZE: i32 = 16;
+
+/// NBT tag ids of the array types section blocks can be stored in.
+const BYTE_ARRAY: i8 = 7;
+const INT_ARRAY: i8 = 11;
+
+/// Game edition and version the blocks are written for.
+const EDITION: &str = "java";
+const VERSION: [i32; 3] = [1, 16, 5];
+
+impl<'a> BlockState<'a> {
+    fn to_construction_tag(&self) -> CompoundTag {
+        let (namespace, name) = match self.name.split_once(':') {
+            Some((namespace, name)) => (namespace, name),
+            None => ("minecraft", self.name)
+        };
+
+        let mut tag = CompoundTag::new();
+        tag.insert_str("namespace", namespace);
+        tag.insert_str("blockname", name);
+        tag.insert_compound_tag("properties", CompoundTag::new());
+        tag.insert_compound_tag_vec("extra_blocks", Vec::new());
+        tag
+    }
+}
+
+/// Entry of the section index table, pointing to a section in the file.
+struct SectionEntry {
+    min: BlockPos,
+    shape: BlockPos,
+    position: i32,
+    length: i32
+}
+
+impl SectionEntry {
+    /// Packed like a numpy record, in native (little endian) byte order.
+    fn write_to(&self, buffer: &mut Vec<i8>) {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&self.min.x.to_le_bytes());
+        bytes.extend_from_slice(&self.min.y.to_le_bytes());
+        bytes.extend_from_slice(&self.min.z.to_le_bytes());
+        bytes.push(self.shape.x as u8);
+        bytes.push(self.shape.y as u8);
+        bytes.push(self.shape.z as u8);
+        bytes.extend_from_slice(&self.position.to_le_bytes());
+        bytes.extend_from_slice(&self.length.to_le_bytes());
+        buffer.extend(bytes.into_iter().map(|b| b as i8));
+    }
+}
+
+impl<'a> Schematic<'a> {
+    /// Writes all regions of the schematic as a single Amulet construction.
+    pub fn write_construction_to<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let air = BlockState::new("minecraft:air");
+        let mut palette: HashMap<&BlockState, usize> = HashMap::new();
+        palette.insert(&air, 0);
+
+        // Assign palette indices in block order, so the output is deterministic
+        let mut region_blocks: Vec<_> = self.regions.iter().flat_map(|region| &region.blocks).collect();
+        region_blocks.sort_by_key(|(pos, _state)| (pos.x, pos.y, pos.z));
+
+        let mut blocks: HashMap<BlockPos, usize> = HashMap::new();
+        for (&pos, &state) in region_blocks {
+            let next_index = palette.len();
+            let index = *palette.entry(state).or_insert(next_index);
+            blocks.insert(pos, index);
+        }
+
+        let min = blocks.keys().copied().reduce(BlockPos::min).unwrap_or(BlockPos::zero());
+        let max = blocks.keys().copied().reduce(BlockPos::max).map(|pos| pos + BlockPos::one()).unwrap_or(BlockPos::zero());
+
+        // Group the blocks into sections
+        let section_of = |pos: BlockPos| BlockPos::new(
+            pos.x.div_euclid(SECTION_SIZE),
+            pos.y.div_euclid(SECTION_SIZE),
+            pos.z.div_euclid(SECTION_SIZE)
+        );
+        let mut sections: HashMap<BlockPos, Vec<(BlockPos, usize)>> = HashMap::new();
+        for (&pos, &index) in &blocks {
+            sections.entry(section_of(pos)).or_default().push((pos, index));
+        }
+        for section in sections.values_mut() {
+            section.sort_by_key(|(pos, _index)| (pos.x, pos.y, pos.z));
+        }
+        let mut section_keys: Vec<_> = sections.keys().copied().collect();
+        section_keys.sort_by_key(|key| (key.x, key.y, key.z));
+
+        let mut buffer: Vec<u8> = Vec::new();
+        buffer.extend_from_slice(MAGIC_NUMBER);
+        buffer.push(FORMAT_VERSION);
+
+        let mut index_table: Vec<i8> = Vec::new();
+        for key in section_keys {
+            let section_min = BlockPos::new(key.x * SECTION_SIZE, key.y * SECTION_SIZE, key.z * SECTION_SIZE);
+            let section_max = section_min + BlockPos::new(SECTION_SIZE, SECTION_SIZE, SECTION_SIZE);
+            let section_min = section_min.max(min);
+            let shape = section_max.min(max) - section_min;
+
+            // Blocks are stored in x, y, z order with z changing fastest,
+            // indexing into a palette local to the section.
+            let mut local_palette = vec![0];
+            let mut local_indices: HashMap<usize, i32> = HashMap::new();
+            local_indices.insert(0, 0);
+            let mut section_blocks = vec![0i32; (shape.x * shape.y * shape.z) as usize];
+            for &(pos, index) in &sections[&key] {
+                let local = pos - section_min;
+                let next_index = local_indices.len() as i32;
+                let local_index = *local_indices.entry(index).or_insert_with(|| {
+                    local_palette.push(index as i32);
+                    next_index
+                });
+                section_blocks[((local.x * shape.y + local.y) * shape.z + local.z) as usize] = local_index;
+            }
+
+            let mut section_tag = CompoundTag::new();
+            section_tag.insert_compound_tag_vec("entities", Vec::new());
+            section_tag.insert_compound_tag_vec("block_entities", Vec::new());
+            // Bytes only fit local palettes of up to 128 states
+            if local_palette.len() <= i8::MAX as usize + 1 {
+                section_tag.insert_i8("blocks_array_type", BYTE_ARRAY);
+                section_tag.insert_i8_vec("blocks", section_blocks.into_iter().map(|index| index as i8).collect());
+            } else {
+                section_tag.insert_i8("blocks_array_type", INT_ARRAY);
+                section_tag.insert_i32_vec("blocks", section_blocks);
+            }
+            section_tag.insert_i32_vec("block_palette", local_palette);
+
+            let position = buffer.len();
+            write_gzip_compound_tag(&mut buffer, &section_tag)?;
+            let entry = SectionEntry {
+                min: section_min,
+                shape,
+                position: position as i32,
+                length: (buffer.len() - position) as i32
+            };
+            entry.write_to(&mut index_table);
+        }
+
+        let mut palette_tags = vec![None; palette.len()];
+        for (&state, &index) in &palette {
+            palette_tags[index] = Some(state.to_construction_tag());
+        }
+        let palette_tags = palette_tags.into_iter().map(|t| t.unwrap()).collect::<Vec<_>>();
+
+        let mut export_version = CompoundTag::new();
+        export_version.insert_str("edition", EDITION);
+        export_version.insert("version", Tag::List(VERSION.iter().map(|&v| Tag::Int(v)).collect()));
+
+        let mut metadata = CompoundTag::new();
+        metadata.insert_str("created_with", "minecraft-schematics");
+        metadata.insert_i32_vec("selection_boxes", vec![min.x, min.y, min.z, max.x, max.y, max.z]);
+        metadata.insert_i8("section_version", SECTION_VERSION);
+        metadata.insert_compound_tag("export_version", export_version);
+        metadata.insert_i8_vec("section_index_table", index_table);
+        metadata.insert_compound_tag_vec("block_palette", palette_tags);
+
+        let metadata_start = buffer.len() as u32;
+        write_gzip_compound_tag(&mut buffer, &metadata)?;
+        buffer.extend_from_slice(&metadata_start.to_be_bytes());
+        buffer.extend_from_slice(MAGIC_NUMBER);
+
+        writer.write_all(&buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nbt::decode::read_gzip_compound_tag;
+
+    use super::*;
+    use crate::Region;
+
+    /// Writes `schematic` and splits the file into its metadata and sections.
+    fn read_back(schematic: &Schematic) -> (CompoundTag, Vec<(SectionEntry, CompoundTag)>) {
+        let mut buffer = Vec::new();
+        schematic.write_construction_to(&mut buffer).unwrap();
+
+        assert_eq!(&buffer[.. 8], MAGIC_NUMBER);
+        assert_eq!(buffer[8], FORMAT_VERSION);
+        assert_eq!(&buffer[buffer.len() - 8 ..], MAGIC_NUMBER);
+
+        let offset = &buffer[buffer.len() - 12 .. buffer.len() - 8];
+        let metadata_start = u32::from_be_bytes([offset[0], offset[1], offset[2], offset[3]]) as usize;
+        let metadata = read_gzip_compound_tag(&mut &buffer[metadata_start ..]).unwrap();
+
+        let table: Vec<u8> = metadata.get_i8_vec("section_index_table").unwrap().iter().map(|&b| b as u8).collect();
+        assert_eq!(table.len() % 23, 0);
+        let int = |bytes: &[u8]| i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let sections = table.chunks(23).map(|record| {
+            let entry = SectionEntry {
+                min: BlockPos::new(int(&record[0 ..]), int(&record[4 ..]), int(&record[8 ..])),
+                shape: BlockPos::new(record[12] as i32, record[13] as i32, record[14] as i32),
+                position: int(&record[15 ..]),
+                length: int(&record[19 ..])
+            };
+            let start = entry.position as usize;
+            let section = read_gzip_compound_tag(&mut &buffer[start .. start + entry.length as usize]).unwrap();
+            (entry, section)
+        }).collect();
+
+        (metadata, sections)
+    }
+
+    fn block_name(metadata: &CompoundTag, index: i32) -> String {
+        let palette = metadata.get_compound_tag_vec("block_palette").unwrap();
+        let block = palette[index as usize];
+        format!("{}:{}", block.get_str("namespace").unwrap(), block.get_str("blockname").unwrap())
+    }
+
+    #[test]
+    fn sections_round_trip() {
+        let stone = BlockState::new("minecraft:stone");
+        let chest = BlockState::new("chest");
+        let mut region = Region::new("test");
+        region.fill(BlockPos::new(0, 0, 0), BlockPos::new(20, 0, 0), &stone);
+        region.set_block_state(BlockPos::new(17, 1, 0), &chest);
+        let mut schematic = Schematic::new();
+        schematic.add_region(region);
+
+        let (metadata, sections) = read_back(&schematic);
+        assert_eq!(*metadata.get_i32_vec("selection_boxes").unwrap(), vec![0, 0, 0, 21, 2, 1]);
+        assert_eq!(sections.len(), 2);
+
+        let (entry, section) = &sections[1];
+        assert_eq!((entry.min, entry.shape), (BlockPos::new(16, 0, 0), BlockPos::new(5, 2, 1)));
+        assert_eq!(section.get_i8("blocks_array_type").unwrap(), BYTE_ARRAY);
+
+        let blocks = section.get_i8_vec("blocks").unwrap();
+        let local_palette = section.get_i32_vec("block_palette").unwrap();
+        let at = |x: i32, y: i32| {
+            let local = blocks[((x - entry.min.x) * entry.shape.y + y) as usize];
+            block_name(&metadata, local_palette[local as usize])
+        };
+        assert_eq!(at(16, 0), "minecraft:stone");
+        assert_eq!(at(17, 1), "minecraft:chest");
+        assert_eq!(at(18, 1), "minecraft:air");
+    }
+
+    #[test]
+    fn large_palettes_use_int_arrays() {
+        let names: Vec<_> = (0 .. 200).map(|i| format!("minecraft:block_{}", i)).collect();
+        let states: Vec<_> = names.iter().map(|name| BlockState::new(name)).collect();
+        let mut region = Region::new("test");
+        for (i, state) in states.iter().enumerate() {
+            region.set_block_state(BlockPos::new(i as i32 % 16, 0, i as i32 / 16), state);
+        }
+        let mut schematic = Schematic::new();
+        schematic.add_region(region);
+
+        let (metadata, sections) = read_back(&schematic);
+        assert_eq!(sections.len(), 1);
+
+        let (entry, section) = &sections[0];
+        assert_eq!(section.get_i8("blocks_array_type").unwrap(), INT_ARRAY);
+        let blocks = section.get_i32_vec("blocks").unwrap();
+        let local_palette = section.get_i32_vec("block_palette").unwrap();
+        for i in 0 .. 200 {
+            let (x, z) = (i % 16, i / 16);
+            let local = blocks[(x * entry.shape.y * entry.shape.z + z) as usize];
+            assert_eq!(block_name(&metadata, local_palette[local as usize]), names[i as usize]);
+        }
+    }
+}
@@ -2,6 +2,11 @@ use std::{collections::HashMap, io::{Error, Write}, ops::{Add, Sub}, usize};
 
 use nbt::{CompoundTag, encode::write_gzip_compound_tag};
 
+mod bounds;
+mod construction;
+
+pub use bounds::{read_litematic_bounds, read_sponge_bounds};
+
 #[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub struct BlockPos {
     x: i32,
@@ -22,6 +27,18 @@ impl BlockPos {
         BlockPos::new(1, 1, 1)
     }
 
+    pub fn x(&self) -> i32 {
+        self.x
+    }
+
+    pub fn y(&self) -> i32 {
+        self.y
+    }
+
+    pub fn z(&self) -> i32 {
+        self.z
+    }
+
     pub fn min(self, other: BlockPos) -> BlockPos {
         BlockPos {
             x: self.x.min(other.x),
@@ -136,6 +153,17 @@ impl<'a> Region<'a> {
         }
     }
 
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// The minimum and maximum block positions of the region, if it isn't empty.
+    pub fn bounds(&self) -> Option<(BlockPos, BlockPos)> {
+        let min = self.blocks.keys().copied().reduce(BlockPos::min)?;
+        let max = self.blocks.keys().copied().reduce(BlockPos::max)?;
+        Some((min, max))
+    }
+
     pub fn to_tag(&self) -> CompoundTag {
         let position = self.blocks.keys().map(|p| *p).reduce(BlockPos::min).unwrap_or(BlockPos::zero());
         let blocks = self.blocks.iter().map(|(&pos, &state)| (pos - position, state)).collect::<HashMap<_, _>>();
@@ -227,10 +255,10 @@ impl<'a> Schematic<'a> {
             metadata.insert_str("Name", name);
         }
         if let Some(author) = self.author {
-            metadata.insert_str("Name", author);
+            metadata.insert_str("Author", author);
         }
         if let Some(description) = self.description {
-            metadata.insert_str("Name", description);
+            metadata.insert_str("Description", description);
         }
         metadata.insert_i32("RegionCount", self.regions.len() as i32);
 
@@ -0,0 +1,540 @@
+use std::{cmp::Ordering, collections::{HashMap, HashSet, VecDeque}};
+
+use crate::parallel;
+
+/// Remaining targets per thread below which splitting the work of a routing step costs more than it saves.
+const PARALLEL_TARGETS: usize = 1024;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ChunkType {
+    Connecting,
+    Target
+}
+
+pub fn dist(pos1: &(i32, i32), pos2: &(i32, i32)) -> i32 {
+    (pos1.0 - pos2.0).abs() + (pos1.1 - pos2.1).abs()
+}
+
+/// Corridor length needed to get from `pos` to within `view_distance` of `target`.
+pub fn reach_dist(pos: &(i32, i32), target: &(i32, i32), view_distance: i32) -> i32 {
+    ((pos.0 - target.0).abs() - view_distance).max(0) + ((pos.1 - target.1).abs() - view_distance).max(0)
+}
+
+/// The chunk closest to `pos` that is within `view_distance` of `target`.
+pub fn closest_in_view(pos: &(i32, i32), target: &(i32, i32), view_distance: i32) -> (i32, i32) {
+    (
+        pos.0.clamp(target.0 - view_distance, target.0 + view_distance),
+        pos.1.clamp(target.1 - view_distance, target.1 + view_distance)
+    )
+}
+
+/// Number of blocks needed to connect `chunks` loaded chunks: each link between two chunks
+/// takes 16 blocks of floor and a marker, plus the floor block in the root chunk.
+fn blocks_for(chunks: usize) -> usize {
+    match chunks.saturating_sub(1) {
+        0 => 0,
+        links => links * 17 + 1
+    }
+}
+
+/// Block offset inside a chunk of the edge facing in the direction of `step`.
+fn edge_offset(step: i32) -> i32 {
+    match step {
+        1 => 15,
+        -1 => 0,
+        _ => 8
+    }
+}
+
+/// Block position of the center of a chunk.
+pub fn chunk_center(chunk: (i32, i32)) -> (i32, i32) {
+    (chunk.0 * 16 + 8, chunk.1 * 16 + 8)
+}
+
+/// A straight corridor run between two chunk positions.
+/// `from` is the end that was already loaded when the segment was routed.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Segment {
+    pub from: (i32, i32),
+    pub to: (i32, i32)
+}
+
+impl Segment {
+    pub fn step(&self) -> (i32, i32) {
+        ((self.to.0 - self.from.0).signum(), (self.to.1 - self.from.1).signum())
+    }
+
+    pub fn len(&self) -> i32 {
+        dist(&self.from, &self.to)
+    }
+
+    /// All chunks covered by the segment, from `from` to `to` inclusive.
+    pub fn chunks(&self) -> impl Iterator<Item = (i32, i32)> {
+        let from = self.from;
+        let step = self.step();
+        (0 ..= self.len()).map(move |i| (from.0 + step.0 * i, from.1 + step.1 * i))
+    }
+
+    /// Block positions of the markers, one at the edge of each chunk
+    /// facing the next chunk along the segment.
+    pub fn markers(&self) -> impl Iterator<Item = (i32, i32)> {
+        let step = self.step();
+        self.chunks()
+            .take(self.len() as usize)
+            .map(move |chunk| (chunk.0 * 16 + edge_offset(step.0), chunk.1 * 16 + edge_offset(step.1)))
+    }
+}
+
+/// A target that isn't connected yet, with the loaded chunk closest to it.
+#[derive(Copy, Clone)]
+struct Pending {
+    pos: (i32, i32),
+    /// Corridor length still needed to reach the target
+    dist: i32,
+    attach: (i32, i32),
+    /// Spare targets are only connected while the plan stays within the block budget
+    optional: bool
+}
+
+/// The set of loaded chunks together with the corridor segments connecting them.
+pub struct Corridors {
+    root: (i32, i32),
+    chunks: HashMap<(i32, i32), ChunkType>,
+    segments: Vec<Segment>,
+    /// Chunks corridors must not pass through
+    blocked: HashSet<(i32, i32)>,
+    /// Targets no corridor could be routed to
+    unreachable: Vec<(i32, i32)>,
+    /// Optional targets left out to stay within the block budget
+    skipped: Vec<(i32, i32)>,
+    /// Prefer routing along z first when both directions load equally many chunks
+    z_first: bool
+}
+
+impl Corridors {
+    pub fn new(root: (i32, i32)) -> Corridors {
+        let mut chunks = HashMap::new();
+        chunks.insert(root, ChunkType::Target);
+        Corridors {
+            root,
+            chunks,
+            segments: Vec::new(),
+            blocked: HashSet::new(),
+            unreachable: Vec::new(),
+            skipped: Vec::new(),
+            z_first: false
+        }
+    }
+
+    /// Grows the corridors from `root` (Prim's method), connecting each target
+    /// to the nearest chunk that is already loaded, be it a target or a corridor.
+    /// With a view distance, corridors only need to come close enough to each target.
+    /// Corridors avoid the `blocked` chunks, detouring around them where necessary.
+    /// The `spares` are connected after all targets, nearest first, as long as
+    /// the corridors stay within `max_blocks`. The others are left out.
+    pub fn plan(
+        root: (i32, i32),
+        targets: &[(i32, i32)],
+        spares: &[(i32, i32)],
+        blocked: &HashSet<(i32, i32)>,
+        view_distance: i32,
+        max_blocks: usize,
+        z_first: bool
+    ) -> Corridors {
+        let mut corridors = Corridors::new(root);
+        corridors.blocked = blocked.clone();
+        corridors.z_first = z_first;
+
+        let pending = |&pos: &(i32, i32), optional: bool| Pending {
+            pos,
+            dist: reach_dist(&root, &pos, view_distance),
+            attach: root,
+            optional
+        };
+        let mut remaining: Vec<_> = targets.iter().map(|pos| pending(pos, false))
+            .chain(spares.iter().map(|pos| pending(pos, true)))
+            .collect();
+        let mut new_chunks = Vec::new();
+
+        loop {
+            // Update the distances to the chunks loaded last, and find the connected and the closest targets.
+            // Many targets are split across threads, the parts are merged in order.
+            let parts = parallel::map_parts_mut(&mut remaining, PARALLEL_TARGETS, |start, part| {
+                let mut connected = Vec::new();
+                let mut closest: Option<((bool, i32), usize)> = None;
+                for (index, target) in (start ..).zip(part.iter_mut()) {
+                    for chunk in &new_chunks {
+                        let dist = reach_dist(chunk, &target.pos, view_distance);
+                        if dist < target.dist {
+                            target.dist = dist;
+                            target.attach = *chunk;
+                        }
+                    }
+                    if target.dist == 0 {
+                        connected.push(index);
+                    } else if closest.is_none_or(|(key, _)| (target.optional, target.dist) < key) {
+                        closest = Some(((target.optional, target.dist), index));
+                    }
+                }
+                (connected, closest)
+            });
+            let connected: Vec<usize> = parts.iter().flat_map(|(connected, _)| connected.iter().copied()).collect();
+            let closest = parts.iter()
+                .filter_map(|(_, closest)| *closest)
+                .min()
+                .map(|(_, index)| index - connected.partition_point(|&c| c < index));
+
+            // Targets passed on the way are connected for free
+            for &index in &connected {
+                corridors.mark_target(remaining[index].pos);
+            }
+            remaining.retain(|target| target.dist > 0);
+
+            let index = match closest {
+                Some(index) => index,
+                None => break
+            };
+            let target = remaining[index];
+            new_chunks.clear();
+
+            let end = closest_in_view(&target.attach, &target.pos, view_distance);
+            let path = corridors.route(end, target.attach, &remaining, view_distance)
+                .or_else(|| corridors.detour(target.pos, view_distance));
+            let path = match path {
+                Some(path) => path,
+                None => {
+                    remaining.swap_remove(index);
+                    corridors.unreachable.push(target.pos);
+                    continue;
+                }
+            };
+
+            // The path ends at a loaded chunk, all its other chunks are new
+            if target.optional && blocks_for(corridors.chunks.len() + path.len() - 1) > max_blocks {
+                remaining.swap_remove(index);
+                corridors.skipped.push(target.pos);
+                continue;
+            }
+            new_chunks = corridors.add_path(path);
+        }
+
+        corridors.merge_segments();
+        corridors
+    }
+
+    pub fn root(&self) -> (i32, i32) {
+        self.root
+    }
+
+    pub fn chunks(&self) -> &HashMap<(i32, i32), ChunkType> {
+        &self.chunks
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn unreachable(&self) -> &[(i32, i32)] {
+        &self.unreachable
+    }
+
+    pub fn skipped(&self) -> &[(i32, i32)] {
+        &self.skipped
+    }
+
+    /// Number of blocks needed to build the corridors.
+    pub fn block_count(&self) -> usize {
+        blocks_for(self.chunks.len())
+    }
+
+    pub fn is_loaded(&self, pos: &(i32, i32)) -> bool {
+        self.chunks.contains_key(pos)
+    }
+
+    /// Marks an already loaded chunk as a target chunk.
+    pub fn mark_target(&mut self, pos: (i32, i32)) {
+        if let Some(typ) = self.chunks.get_mut(&pos) {
+            *typ = ChunkType::Target;
+        }
+    }
+
+    /// The two L-shaped paths from `target` to `attach`, walked from the target
+    /// and cut off at the first chunk that is already loaded.
+    /// Paths running through blocked chunks are `None`.
+    fn candidate_paths(&self, target: (i32, i32), attach: (i32, i32)) -> [Option<Vec<(i32, i32)>>; 2] {
+        let corners = [(attach.0, target.1), (target.0, attach.1)];
+        let mut paths = [Some(Vec::new()), Some(Vec::new())];
+
+        for (path, &corner) in paths.iter_mut().zip(corners.iter()) {
+            let legs = [Segment { from: target, to: corner }, Segment { from: corner, to: attach }];
+            let mut chunks = Vec::new();
+            for pos in legs.iter().flat_map(|leg| leg.chunks()) {
+                if chunks.last() == Some(&pos) {
+                    continue;
+                }
+                if self.blocked.contains(&pos) {
+                    *path = None;
+                    break;
+                }
+                chunks.push(pos);
+                if self.is_loaded(&pos) {
+                    break;
+                }
+            }
+            if path.is_some() {
+                *path = Some(chunks);
+            }
+        }
+
+        paths
+    }
+
+    /// The L-shaped path from `target` to the loaded chunk `attach` that loads
+    /// the fewest new chunks. Loaded chunks along the way are reused,
+    /// so the path ends as soon as it reaches one. Of equally long paths, the one
+    /// bringing the `remaining` targets closer is preferred, so later corridors can reuse it.
+    fn route(
+        &self,
+        target: (i32, i32),
+        attach: (i32, i32),
+        remaining: &[Pending],
+        view_distance: i32
+    ) -> Option<Vec<(i32, i32)>> {
+        if self.blocked.contains(&target) {
+            return None;
+        }
+
+        // Corridor length the path saves the remaining targets
+        let savings = |path: &[(i32, i32)]| -> i64 {
+            let parts = parallel::map_parts(remaining, PARALLEL_TARGETS, |_, part| {
+                part.iter()
+                    .map(|pending| {
+                        let dist = path.iter().map(|chunk| reach_dist(chunk, &pending.pos, view_distance)).min();
+                        (pending.dist - dist.unwrap_or(pending.dist)).max(0) as i64
+                    })
+                    .sum::<i64>()
+            });
+            parts.into_iter().sum()
+        };
+
+        match self.candidate_paths(target, attach) {
+            [Some(x_first), Some(z_first)] => {
+                let order = z_first.len().cmp(&x_first.len())
+                    .then_with(|| savings(&x_first).cmp(&savings(&z_first)));
+                Some(match order {
+                    Ordering::Less => z_first,
+                    Ordering::Equal if self.z_first => z_first,
+                    _ => x_first
+                })
+            },
+            [x_first, z_first] => x_first.or(z_first)
+        }
+    }
+
+    /// The shortest path from any unblocked chunk within `view_distance` of `target`
+    /// to a loaded chunk, walking around blocked chunks.
+    fn detour(&self, target: (i32, i32), view_distance: i32) -> Option<Vec<(i32, i32)>> {
+        // Search only slightly beyond everything that could be in the way
+        let margin = view_distance + 1;
+        let mut min = (target.0 - margin, target.1 - margin);
+        let mut max = (target.0 + margin, target.1 + margin);
+        for pos in self.chunks.keys().chain(self.blocked.iter()) {
+            min = (min.0.min(pos.0 - 1), min.1.min(pos.1 - 1));
+            max = (max.0.max(pos.0 + 1), max.1.max(pos.1 + 1));
+        }
+
+        let mut previous = HashMap::new();
+        let mut queue = VecDeque::new();
+        for x in target.0 - view_distance ..= target.0 + view_distance {
+            for z in target.1 - view_distance ..= target.1 + view_distance {
+                if !self.blocked.contains(&(x, z)) {
+                    previous.insert((x, z), (x, z));
+                    queue.push_back((x, z));
+                }
+            }
+        }
+
+        while let Some(pos) = queue.pop_front() {
+            if self.is_loaded(&pos) {
+                let mut path = vec![pos];
+                while previous[path.last().unwrap()] != *path.last().unwrap() {
+                    path.push(previous[path.last().unwrap()]);
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            for step in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                let next = (pos.0 + step.0, pos.1 + step.1);
+                let in_bounds = next.0 >= min.0 && next.0 <= max.0 && next.1 >= min.1 && next.1 <= max.1;
+                if in_bounds && !self.blocked.contains(&next) && !previous.contains_key(&next) {
+                    previous.insert(next, pos);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Adds a path, walked from its new end to an already loaded chunk, as corridor segments.
+    /// Returns the newly loaded chunks.
+    fn add_path(&mut self, mut path: Vec<(i32, i32)>) -> Vec<(i32, i32)> {
+        // Walk from the loaded end towards the target
+        path.reverse();
+        let mut start = 0;
+        let step = |a: (i32, i32), b: (i32, i32)| (b.0 - a.0, b.1 - a.1);
+        for i in 1 .. path.len() {
+            let is_end = i + 1 == path.len();
+            if is_end || step(path[i - 1], path[i]) != step(path[i], path[i + 1]) {
+                self.segments.push(Segment { from: path[start], to: path[i] });
+                start = i;
+            }
+        }
+
+        let new_chunks = path[1 ..].to_vec();
+        for &pos in &new_chunks {
+            self.chunks.insert(pos, ChunkType::Connecting);
+        }
+
+        new_chunks
+    }
+
+    /// Joins segments that continue each other in a straight line,
+    /// so each corridor run is filled exactly once.
+    pub fn merge_segments(&mut self) {
+        let starts: HashMap<_, _> = self.segments.iter()
+            .enumerate()
+            .map(|(index, segment)| ((segment.from, segment.step()), index))
+            .collect();
+
+        let next: Vec<Option<usize>> = self.segments.iter()
+            .map(|segment| starts.get(&(segment.to, segment.step())).copied())
+            .collect();
+
+        let mut is_continuation = vec![false; self.segments.len()];
+        for &index in next.iter().flatten() {
+            is_continuation[index] = true;
+        }
+
+        let mut merged = Vec::new();
+        for (mut index, segment) in self.segments.iter().enumerate() {
+            if is_continuation[index] {
+                continue;
+            }
+            let mut segment = *segment;
+            while let Some(following) = next[index] {
+                segment.to = self.segments[following].to;
+                index = following;
+            }
+            merged.push(segment);
+        }
+
+        self.segments = merged;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use minecraft_schematics::{BlockPos, BlockState, Region};
+
+    use super::*;
+
+    fn plan(targets: &[(i32, i32)], view_distance: i32) -> Corridors {
+        Corridors::plan((0, 0), targets, &[], &HashSet::new(), view_distance, usize::MAX, false)
+    }
+
+    #[test]
+    fn collinear_targets_merge_into_one_segment() {
+        let corridors = plan(&[(3, 0), (6, 0), (9, 0)], 0);
+        assert_eq!(corridors.segments(), &[Segment { from: (0, 0), to: (9, 0) }]);
+        assert_eq!(corridors.chunks().len(), 10);
+    }
+
+    #[test]
+    fn targets_on_corridors_are_reused() {
+        let corridors = plan(&[(5, 0), (2, 0)], 0);
+        assert_eq!(corridors.chunks().len(), plan(&[(5, 0)], 0).chunks().len());
+        assert_eq!(corridors.chunks()[&(2, 0)], ChunkType::Target);
+        assert_eq!(corridors.chunks()[&(1, 0)], ChunkType::Connecting);
+    }
+
+    #[test]
+    fn routes_end_at_loaded_chunks() {
+        // The second target is routed to the corridor, not back to the root
+        let corridors = plan(&[(0, 6), (4, 3)], 0);
+        assert_eq!(corridors.chunks().len(), 7 + 4);
+        assert!(corridors.segments().contains(&Segment { from: (0, 3), to: (4, 3) }));
+    }
+
+    #[test]
+    fn view_distance_shortens_corridors() {
+        assert_eq!(reach_dist(&(0, 0), &(10, -2), 3), 7);
+        assert_eq!(closest_in_view(&(0, 0), &(10, -2), 3), (7, 0));
+
+        let corridors = plan(&[(10, -2)], 3);
+        assert_eq!(corridors.segments(), &[Segment { from: (0, 0), to: (7, 0) }]);
+        assert!(!corridors.is_loaded(&(10, -2)));
+    }
+
+    #[test]
+    fn block_count_matches_schematic() {
+        let corridors = plan(&[(4, 0), (4, 5), (-3, 2), (-3, -6), (8, -1)], 0);
+        let links = corridors.chunks().len() - 1;
+        assert_eq!(corridors.block_count(), 17 * links + 1);
+
+        let floor = BlockState::new("minecraft:white_concrete");
+        let marker = BlockState::new("minecraft:chest");
+        let mut region = Region::new("test");
+        for segment in corridors.segments() {
+            let (start, end) = (chunk_center(segment.from), chunk_center(segment.to));
+            region.fill(BlockPos::new(start.0, 0, start.1), BlockPos::new(end.0, 0, end.1), &floor);
+            for (x, z) in segment.markers() {
+                region.set_block_state(BlockPos::new(x, 1, z), &marker);
+            }
+        }
+        assert_eq!(region.block_count(), corridors.block_count());
+    }
+
+    #[test]
+    fn spares_stay_within_budget() {
+        let spares = [(0, 2), (0, -8), (5, 5)];
+        let unlimited = Corridors::plan((0, 0), &[(4, 0)], &spares, &HashSet::new(), 0, usize::MAX, false);
+        assert!(unlimited.skipped().is_empty());
+
+        // The target and the close spare fit, the other spares would exceed the budget
+        let budget = blocks_for(1 + 4 + 2);
+        let corridors = Corridors::plan((0, 0), &[(4, 0)], &spares, &HashSet::new(), 0, budget, false);
+        assert_eq!(corridors.block_count(), budget);
+        assert!(corridors.is_loaded(&(4, 0)) && corridors.is_loaded(&(0, 2)));
+        assert_eq!(corridors.skipped().len(), 2);
+
+        // Targets are connected regardless of the budget
+        let corridors = Corridors::plan((0, 0), &[(4, 0)], &spares, &HashSet::new(), 0, 0, false);
+        assert!(corridors.is_loaded(&(4, 0)));
+        assert_eq!(corridors.skipped().len(), 3);
+    }
+
+    #[test]
+    fn blocked_chunks_force_a_detour() {
+        let blocked: HashSet<_> = (-2 ..= 2).map(|z| (3, z)).collect();
+        let corridors = Corridors::plan((0, 0), &[(6, 0)], &[], &blocked, 0, usize::MAX, false);
+
+        assert!(corridors.unreachable().is_empty());
+        assert_eq!(corridors.chunks()[&(6, 0)], ChunkType::Target);
+        assert!(corridors.chunks().keys().all(|chunk| !blocked.contains(chunk)));
+        for segment in corridors.segments() {
+            assert!(segment.chunks().all(|chunk| corridors.is_loaded(&chunk) && !blocked.contains(&chunk)));
+        }
+        // Around the wall: 6 across and 3 out and back
+        assert_eq!(corridors.chunks().len(), 1 + 6 + 2 * 3);
+    }
+
+    #[test]
+    fn enclosed_targets_are_unreachable() {
+        let blocked: HashSet<_> = [(5, 0), (7, 0), (6, 1), (6, -1)].iter().copied().collect();
+        let corridors = Corridors::plan((0, 0), &[(6, 0)], &[], &blocked, 0, usize::MAX, false);
+        assert_eq!(corridors.unreachable(), &[(6, 0)]);
+        assert_eq!(corridors.chunks().len(), 1);
+    }
+}
@@ -0,0 +1,124 @@
+use std::{collections::HashSet, error::Error, fs::{self, File}, io::BufReader, path::Path};
+
+use minecraft_schematics::{BlockPos, read_litematic_bounds, read_sponge_bounds};
+
+/// The chunks covered by an existing structure, like a perimeter or a farm.
+#[derive(Default)]
+pub struct Footprint {
+    chunks: HashSet<(i32, i32)>
+}
+
+impl Footprint {
+    /// Loads a footprint from a Litematica schematic, a Sponge schematic (`.schem`)
+    /// or a text file listing chunk rectangles.
+    /// Litematica schematics don't store where they were saved, so their regions
+    /// are placed relative to the block position `origin`.
+    pub fn load(path: &Path, origin: (i32, i32)) -> Result<Footprint, Box<dyn Error>> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+
+        let boxes = match extension {
+            "litematic" => {
+                let origin = BlockPos::new(origin.0, 0, origin.1);
+                read_litematic_bounds(&mut BufReader::new(File::open(path)?))?
+                    .into_iter()
+                    .map(|(min, max)| (min + origin, max + origin))
+                    .collect()
+            },
+            "schem" => vec![read_sponge_bounds(&mut BufReader::new(File::open(path)?))?],
+            _ => return Footprint::parse_rectangles(&fs::read_to_string(path)?)
+        };
+
+        let mut chunks = HashSet::new();
+        for (min, max) in boxes {
+            for x in min.x() >> 4 ..= max.x() >> 4 {
+                for z in min.z() >> 4 ..= max.z() >> 4 {
+                    chunks.insert((x, z));
+                }
+            }
+        }
+
+        Ok(Footprint { chunks })
+    }
+
+    /// Parses chunk rectangles, one per line as `x1 z1 x2 z2` with inclusive corners,
+    /// or single chunks as `x z`. Lines starting with `#` are ignored.
+    fn parse_rectangles(text: &str) -> Result<Footprint, Box<dyn Error>> {
+        let mut chunks = HashSet::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let values = line.split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|value| !value.is_empty())
+                .map(|value| value.parse::<i32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| format!("Invalid footprint line {}: '{}'", number + 1, line))?;
+
+            let (start, end) = match *values.as_slice() {
+                [x, z] => ((x, z), (x, z)),
+                [x1, z1, x2, z2] => ((x1, z1), (x2, z2)),
+                _ => return Err(format!("Invalid footprint line {}: '{}'", number + 1, line).into())
+            };
+
+            for x in start.0.min(end.0) ..= start.0.max(end.0) {
+                for z in start.1.min(end.1) ..= start.1.max(end.1) {
+                    chunks.insert((x, z));
+                }
+            }
+        }
+
+        Ok(Footprint { chunks })
+    }
+
+    pub fn chunks(&self) -> &HashSet<(i32, i32)> {
+        &self.chunks
+    }
+
+    pub fn contains(&self, chunk: &(i32, i32)) -> bool {
+        self.chunks.contains(chunk)
+    }
+
+    /// The minimum and maximum chunk positions, if the footprint isn't empty.
+    pub fn bounds(&self) -> Option<((i32, i32), (i32, i32))> {
+        let min_x = self.chunks.iter().map(|c| c.0).min()?;
+        let min_z = self.chunks.iter().map(|c| c.1).min()?;
+        let max_x = self.chunks.iter().map(|c| c.0).max()?;
+        let max_z = self.chunks.iter().map(|c| c.1).max()?;
+        Some(((min_x, min_z), (max_x, max_z)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(footprint: &Footprint) -> Vec<(i32, i32)> {
+        let mut chunks: Vec<_> = footprint.chunks().iter().copied().collect();
+        chunks.sort_unstable();
+        chunks
+    }
+
+    #[test]
+    fn rectangles_with_reversed_corners() {
+        let footprint = Footprint::parse_rectangles("1 -1 0 -2").unwrap();
+        assert_eq!(sorted(&footprint), vec![(0, -2), (0, -1), (1, -2), (1, -1)]);
+    }
+
+    #[test]
+    fn single_chunks_comments_and_commas() {
+        let text = "# perimeter\n\n  5,7\n# 100 100\n-3, 2, -3, 3\n";
+        let footprint = Footprint::parse_rectangles(text).unwrap();
+        assert_eq!(sorted(&footprint), vec![(-3, 2), (-3, 3), (5, 7)]);
+        assert_eq!(footprint.bounds(), Some(((-3, 2), (5, 7))));
+    }
+
+    #[test]
+    fn invalid_lines() {
+        assert!(Footprint::parse_rectangles("1 2 3").is_err());
+        assert!(Footprint::parse_rectangles("1 x").is_err());
+        assert!(Footprint::parse_rectangles("").unwrap().bounds().is_none());
+    }
+}
@@ -1,15 +1,20 @@
-#![feature(map_first_last)]
-use std::{collections::{BTreeSet, BinaryHeap, HashMap, HashSet}, error::Error, fs::File, io::BufWriter, path::Path};
+use std::{collections::{BTreeSet, BinaryHeap, HashSet}, error::Error, fs::File, io::{BufWriter, Write}, path::Path, thread, time::{Duration, Instant}};
 
-use petgraph::{Graph, visit::IntoNodeReferences};
 use png::Encoder;
 
-use minecraft_schematics::{BlockState, Region, Schematic};
+use minecraft_schematics::{BlockPos, BlockState, Region, Schematic};
 
-enum ChunkType {
-    Connecting,
-    Target
-}
+mod corridor;
+mod footprint;
+mod optimize;
+mod options;
+mod parallel;
+mod rcon;
+mod route;
+use corridor::{ChunkType, Corridors, chunk_center, dist};
+use footprint::Footprint;
+use options::Options;
+use rcon::Rcon;
 
 fn mix(val: u64) -> u64 {
     let mut hashed = val.wrapping_mul(0x9E3779B97F4A7C15);
@@ -18,10 +23,6 @@ fn mix(val: u64) -> u64 {
     hashed
 }
 
-fn dist(pos1: &(i32, i32), pos2: &(i32, i32)) -> i32 {
-    (pos1.0 - pos2.0).abs() + (pos1.1 - pos2.1).abs()
-}
-
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 struct Chunk {
     x: i32,
@@ -43,110 +44,339 @@ impl PartialOrd for Chunk {
     }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let offset: (i32, i32) = (-20, 20);
-    let width: i32 = 50;
+/// Block the corridor floors are built from.
+const FLOOR_BLOCK: &str = "minecraft:white_concrete";
+/// Block marking each link between two corridor chunks.
+const MARKER_BLOCK: &str = "minecraft:chest";
+
+/// How long to wait for force loaded chunks to load before building over RCON.
+const LOAD_TIMEOUT: Duration = Duration::from_secs(60);
+/// Pause between checking which force loaded chunks are loaded yet.
+const LOAD_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Number of root chunks tried when searching for a plan within the block budget.
+const BUDGET_ROOTS: usize = 32;
+
+/// Plans the corridors from several root chunks close to the center of the cluster,
+/// with both routing preferences, and returns the plan needing the fewest blocks,
+/// falling back to `default`. Stops early once a plan fits into `max_blocks`.
+fn plan_within_budget(
+    targets: &[(i32, i32)],
+    spares: &[(i32, i32)],
+    blocked: &HashSet<(i32, i32)>,
+    view_distance: i32,
+    max_blocks: usize,
+    default: Corridors
+) -> Corridors {
+    let count = targets.len() as i64;
+    let center = (
+        (targets.iter().map(|t| t.0 as i64).sum::<i64>() / count) as i32,
+        (targets.iter().map(|t| t.1 as i64).sum::<i64>() / count) as i32
+    );
+
+    let mut roots = targets.to_vec();
+    roots.sort_by_key(|root| dist(root, &center));
+    roots.truncate(BUDGET_ROOTS);
+
+    let variants: Vec<_> = roots.iter()
+        .flat_map(|&root| [(root, false), (root, true)])
+        .collect();
+
+    // Plan one batch of variants per thread at a time, keeping the order for picking the best
+    let mut best = default;
+    for batch in variants.chunks(parallel::threads()) {
+        let plans = parallel::map(batch, |&(root, z_first)| {
+            Corridors::plan(root, targets, spares, blocked, view_distance, max_blocks, z_first)
+        });
+        for corridors in plans {
+            if corridors.block_count() < best.block_count() {
+                best = corridors;
+            }
+            if best.block_count() <= max_blocks {
+                return best;
+            }
+        }
+    }
+
+    best
+}
+
+/// Picks the `count` spare candidates with hashes closest to the ones the cluster needs,
+/// preferring candidates within the bounds of the cluster.
+fn pick_spares(candidates: &[Chunk], cluster: &BTreeSet<Chunk>, count: usize) -> Vec<Chunk> {
+    let min_x = cluster.iter().map(|c| c.x).min().unwrap_or(0);
+    let max_x = cluster.iter().map(|c| c.x).max().unwrap_or(0);
+    let min_z = cluster.iter().map(|c| c.z).min().unwrap_or(0);
+    let max_z = cluster.iter().map(|c| c.z).max().unwrap_or(0);
+    let outside = |c: &Chunk| c.x < min_x || c.x > max_x || c.z < min_z || c.z > max_z;
+
+    let mut spares = candidates.to_vec();
+    spares.sort_by(|a, b| outside(a).cmp(&outside(b)).then_with(|| a.cmp(b)));
+    spares.truncate(count);
+    spares
+}
+
+/// Blocks the corridors to `cluster` and its `spares` need, with the default plan.
+fn corridor_blocks(
+    cluster: &BTreeSet<Chunk>,
+    spares: &[Chunk],
+    footprint: &Footprint,
+    view_distance: i32,
+    max_blocks: usize
+) -> usize {
+    let position = |chunk: &Chunk| (chunk.x, chunk.z);
+    let targets: Vec<_> = cluster.iter().map(position).filter(|t| !footprint.contains(t)).collect();
+    let spares: Vec<_> = spares.iter().map(position).filter(|t| !footprint.contains(t)).collect();
+    match targets.first() {
+        Some(&root) => {
+            Corridors::plan(root, &targets, &spares, footprint.chunks(), view_distance, max_blocks, false).block_count()
+        },
+        None => 0
+    }
+}
+
+fn main() {
+    let options = match Options::from_args() {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(error) = run(&options) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+/// Checks which of `chunks` are loaded on the server until all of them are, or `LOAD_TIMEOUT`
+/// passes. Returns the chunks that are still not loaded.
+fn wait_until_loaded(
+    rcon: &mut Rcon,
+    chunks: &[(i32, i32)],
+    y: i32,
+    dimension: Option<&str>
+) -> Result<Vec<(i32, i32)>, std::io::Error> {
+    let start = Instant::now();
+    let mut unloaded = chunks.to_vec();
+    loop {
+        let mut still_unloaded = Vec::new();
+        for &chunk in &unloaded {
+            if !rcon.command(&route::loaded_command(chunk, y, dimension))?.contains("Test passed") {
+                still_unloaded.push(chunk);
+            }
+        }
+        unloaded = still_unloaded;
+
+        if unloaded.is_empty() || start.elapsed() >= LOAD_TIMEOUT {
+            return Ok(unloaded);
+        }
+        thread::sleep(LOAD_POLL_INTERVAL);
+    }
+}
+
+fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    let mut offset: (i32, i32) = (-20, 20);
+    let mut width: i32 = 50;
+    // Rows are added east of the searched area, or alternately east and west when centred on a footprint
+    let mut centred = false;
     let cluster_size: u64 = 810;
     let hash_size: u64 = 2048;
 
+    let footprint = match &options.footprint {
+        Some(path) => Footprint::load(Path::new(path), options.footprint_origin)?,
+        None => Footprint::default()
+    };
+
+    // Centre the search on the footprint, covering all of its rows
+    if let Some((min, max)) = footprint.bounds() {
+        println!("Footprint covers {} chunks", footprint.chunks().len());
+        let rows = max.1 - min.1 + 1;
+        width = width.max(rows);
+        offset = ((min.0 + max.0).div_euclid(2), min.1 - (width - rows) / 2);
+        centred = true;
+    }
+
     assert!(hash_size.is_power_of_two());
     let mask = hash_size - 1;
+    let hash_at = |x: i32, z: i32| mix(((z as u64) << 32) | (((x as u64) << 32) >> 32)) & mask;
+
+    // Spare chunks have hashes in a window just below the hashes the cluster needs
+    let spare_count = (cluster_size as f64 * options.tolerance).ceil() as u64;
+    if cluster_size + spare_count > hash_size {
+        return Err(format!("Tolerance too large, the cluster can have at most {} spares", hash_size - cluster_size).into());
+    }
 
     println!("Looking for {} cluster chunks...", cluster_size);
 
     let mut cluster_chunks = BTreeSet::new();
     let mut potential_chunks: BinaryHeap<Chunk> = BinaryHeap::new();
+    let mut spare_candidates = Vec::new();
 
     let min_hash = hash_size - cluster_size;
+    let spare_min_hash = min_hash - spare_count;
     let mut length: i32 = 0;
 
-    while cluster_chunks.len() < cluster_size as usize {
+    while cluster_chunks.len() < cluster_size as usize || spare_candidates.len() < spare_count as usize {
         let max_hash = min_hash + cluster_chunks.len() as u64;
 
         // Get lowest hash and see if it's sufficient
         let min_chunk = potential_chunks.peek();
-        if min_chunk.is_some() && min_chunk.unwrap().hash <= max_hash {
+        if cluster_chunks.len() < cluster_size as usize && min_chunk.is_some() && min_chunk.unwrap().hash <= max_hash {
             let chunk = potential_chunks.pop().unwrap();
             cluster_chunks.insert(chunk);
         }
         else {
             // Else add another row of chunks
+            let x = if centred && length % 2 == 1 {
+                offset.0 -= 1;
+                offset.0
+            } else {
+                offset.0 + length
+            };
             for z in offset.1 .. offset.1 + width {
-                let long = ((z as u64) << 32) | ((((length + offset.0) as u64) << 32) >> 32);
-                let hash = mix(long) & mask;
-                if hash >= min_hash {
+                let hash = hash_at(x, z);
+                if hash >= spare_min_hash {
                     let chunk = Chunk {
-                        x: length + offset.0,
-                        z: z,
-                        hash: hash
+                        x,
+                        z,
+                        hash
                     };
-                    potential_chunks.push(chunk);
+                    if hash >= min_hash {
+                        potential_chunks.push(chunk);
+                    } else {
+                        spare_candidates.push(chunk);
+                    }
                 }
             }
-            length = length + 1;
+            length += 1;
+        }
+    }
+
+    println!("Found {} valid cluster chunks!", cluster_chunks.len());
+    let mut spare_chunks = pick_spares(&spare_candidates, &cluster_chunks, spare_count as usize);
+    let view_distance = options.view_distance.unwrap_or(0);
+    let max_blocks = options.max_blocks.unwrap_or(usize::MAX);
+
+    if let Some(seconds) = options.optimize {
+        // Search twice the rows the greedy selection needed, extending both sides when centred
+        let window = length * 2;
+        let start = if centred { offset.0 - length / 2 } else { offset.0 };
+        println!("Optimizing the cluster in {} x {} chunks for {} seconds...", window, width, seconds);
+
+        let mut candidates = Vec::new();
+        let mut spare_candidates = Vec::new();
+        for x in start .. start + window {
+            for z in offset.1 .. offset.1 + width {
+                let hash = hash_at(x, z);
+                if hash >= min_hash {
+                    candidates.push(Chunk { x, z, hash });
+                } else if hash >= spare_min_hash {
+                    spare_candidates.push(Chunk { x, z, hash });
+                }
+            }
+        }
+
+        let greedy: Vec<_> = cluster_chunks.iter().copied().collect();
+        let optimized: BTreeSet<_> = optimize::anneal(&candidates, &greedy, min_hash, Duration::from_secs_f64(seconds))
+            .into_iter()
+            .collect();
+        let optimized_spares = pick_spares(&spare_candidates, &optimized, spare_count as usize);
+
+        let greedy_blocks = corridor_blocks(&cluster_chunks, &spare_chunks, &footprint, view_distance, max_blocks);
+        let optimized_blocks = corridor_blocks(&optimized, &optimized_spares, &footprint, view_distance, max_blocks);
+        if optimized_blocks < greedy_blocks {
+            println!("Optimized cluster needs {} instead of {} corridor blocks", optimized_blocks, greedy_blocks);
+            cluster_chunks = optimized;
+            spare_chunks = optimized_spares;
+            offset.0 = start;
+            length = window;
+        } else {
+            println!("Optimized cluster needs {} corridor blocks, keeping the greedy cluster with {}", optimized_blocks, greedy_blocks);
         }
     }
 
     let size = (length, width);
 
-    println!("Found {} valid cluster chunks!", cluster_chunks.len());
+    let spares: HashSet<(i32, i32)> = spare_chunks.iter().map(|chunk| (chunk.x, chunk.z)).collect();
+    if !spares.is_empty() {
+        println!("Found {} spare chunks!", spares.len());
+    }
+    cluster_chunks.extend(spare_chunks);
     println!("Searched area: {} x {} chunks", size.0, size.1);
 
 
     println!("Generating tree...");
-    // Generate minimum spanning tree (Prim's method, not efficient)
-    let mut graph = Graph::<Chunk, i32, _>::new_undirected();
-    graph.add_node(cluster_chunks.pop_first().unwrap());
-
-    while !cluster_chunks.is_empty() {
-        let (index_a, chunk, d) = graph.node_references().map(|(index, node)| {
-            let (&chunk, d) = cluster_chunks.iter()
-                .map(|chunk| (chunk, dist(&(chunk.x, chunk.z), &(node.x, node.z))))
-                .min_by_key(|(_chunk, d)| *d).unwrap();
-            return (index, chunk, d);
-        }).min_by_key(|(_index, _chunk, d)| *d).unwrap();
-
-        let index_b = graph.add_node(chunk);
-        graph.add_edge(index_a, index_b, d);
+    let targets: Vec<(i32, i32)> = cluster_chunks.iter().map(|chunk| (chunk.x, chunk.z)).collect();
+
+    // Chunks of the footprint need no corridors, and corridors may not pass through them
+    let (footprint_members, corridor_targets): (Vec<_>, Vec<_>) = targets.iter()
+        .copied()
+        .partition(|target| footprint.contains(target));
+    if !footprint.chunks().is_empty() {
+        println!("Footprint chunks in the cluster: {}", footprint_members.len());
+    }
+    // Spares are only connected while the corridors stay within the budget
+    let (spare_targets, corridor_targets): (Vec<_>, Vec<_>) = corridor_targets.into_iter()
+        .partition(|target| spares.contains(target));
+    let root = corridor_targets.first().copied().unwrap_or(targets[0]);
+
+    let mut corridors = Corridors::plan(
+        root, &corridor_targets, &spare_targets, footprint.chunks(), view_distance, max_blocks, false
+    );
+
+    if corridors.block_count() > max_blocks {
+        println!("Plan needs {} blocks, searching for a plan within {} blocks...", corridors.block_count(), max_blocks);
+        corridors = plan_within_budget(
+            &corridor_targets, &spare_targets, footprint.chunks(), view_distance, max_blocks, corridors
+        );
+    }
+    if !corridors.skipped().is_empty() {
+        println!("Left out {} spare chunks to stay within {} blocks", corridors.skipped().len(), max_blocks);
+    }
 
-        cluster_chunks.remove(&chunk);
+    if !corridors.unreachable().is_empty() {
+        println!("Warning: {} targets can't be reached around the footprint", corridors.unreachable().len());
     }
 
-    println!("Collecting chunks...");
-    let mut chunks = HashMap::new();
+    if options.view_distance.is_some() {
+        println!("Corridor chunks: {}", corridors.chunks().len());
+    } else {
+        println!("Total chunks loaded: {}", corridors.chunks().len());
+    }
+    println!("Corridor segments: {}", corridors.segments().len());
+    println!("Corridor blocks: {}", corridors.block_count());
 
     let mut img_data = vec![0; size.0 as usize * size.1 as usize];
 
-    for edge in graph.edge_indices() {
-        let (index_a, index_b) = graph.edge_endpoints(edge).unwrap();
-        let pos_a = graph[index_a];
-        let pos_b = graph[index_b];
-        
-        for x in pos_a.x.min(pos_b.x) ..= pos_a.x.max(pos_b.x) {
-            chunks.insert((x, pos_a.z), ChunkType::Connecting);
+    // Corridors may detour and footprints may extend beyond the searched area
+    let pixel = |chunk: &(i32, i32)| {
+        let (x, z) = (chunk.0 - offset.0, chunk.1 - offset.1);
+        if x >= 0 && x < size.0 && z >= 0 && z < size.1 {
+            Some((x + z * size.0) as usize)
+        } else {
+            None
         }
-        
-        for z in pos_a.z.min(pos_b.z) ..= pos_a.z.max(pos_b.z) {
-            chunks.insert((pos_b.x, z), ChunkType::Connecting);
+    };
+
+    println!("Generating image...");
+    for chunk in footprint.chunks() {
+        if let Some(index) = pixel(chunk) {
+            img_data[index] = 63;
         }
     }
-
-    for node in graph.node_indices() {
-        let pos = graph[node];
-        let edge = chunks.insert((pos.x, pos.z), ChunkType::Target);
-        if edge.is_none() {
-            println!("Missing edge at {:?}", (pos.x, pos.z));
+    for (chunk, typ) in corridors.chunks() {
+        if let Some(index) = pixel(chunk) {
+            match typ {
+                ChunkType::Connecting => img_data[index] = 127,
+                ChunkType::Target => img_data[index] = 255,
+            }
         }
     }
-
-    println!("Total chunks loaded: {}", chunks.len());
-
-    println!("Generating image...");
-    for (chunk, typ) in &chunks {
-        let data = &mut img_data[((chunk.0 - offset.0) + (chunk.1 - offset.1) * size.0) as usize];
-        match typ {
-            ChunkType::Connecting => *data = 127,
-            ChunkType::Target => *data = 255,
+    for chunk in &targets {
+        if let Some(index) = pixel(chunk) {
+            img_data[index] = if spares.contains(chunk) { 191 } else { 255 };
         }
     }
 
@@ -161,66 +391,218 @@ fn main() -> Result<(), Box<dyn Error>> {
 
 
     println!("Generating schematic...");
-    let chest = BlockState::new("minecraft:chest");
-    let concrete = BlockState::new("minecraft:concrete");
+    let chest = BlockState::new(MARKER_BLOCK);
+    let concrete = BlockState::new(FLOOR_BLOCK);
     let mut region = Region::new("chests");
 
-    let start = graph[graph.node_indices().min_by_key(|&i| dist(&(graph[i].x, graph[i].z), &offset)).unwrap()];
-    let mut chunks_to_explore = HashSet::new();
-    let mut chunks_connected = HashSet::new();
-    chunks_to_explore.insert((start.x, start.z));
-    chunks_connected.insert((start.x, start.z));
-
-    while !chunks_to_explore.is_empty() {
-        let current_chunks: Vec<(i32, i32)> = chunks_to_explore.drain().collect();
-        for chunk in &current_chunks {
-            let pos = (chunk.0 + 1, chunk.1);
-            if chunks.contains_key(&pos) &&  !chunks_connected.contains(&pos) {
-                let start = (chunk.0 * 16 + 8, 0, chunk.1 * 16 + 8);
-                let end = (pos.0 * 16 + 8, 0, pos.1 * 16 + 8);
-                region.fill(start.into(), end.into(), &concrete);
-                region.set_block_state((chunk.0 * 16 + 15, 1, chunk.1 * 16 + 8).into(), &chest);
-                chunks_connected.insert(pos);
-                chunks_to_explore.insert(pos);
-            }
-            let pos = (chunk.0 - 1, chunk.1);
-            if chunks.contains_key(&pos) &&  !chunks_connected.contains(&pos) {
-                let start = (chunk.0 * 16 + 8, 0, chunk.1 * 16 + 8);
-                let end = (pos.0 * 16 + 8, 0, pos.1 * 16 + 8);
-                region.fill(start.into(), end.into(), &concrete);
-                region.set_block_state((chunk.0 * 16 + 0, 1, chunk.1 * 16 + 8).into(), &chest);
-                chunks_connected.insert(pos);
-                chunks_to_explore.insert(pos);
-            }
-            let pos = (chunk.0, chunk.1 + 1);
-            if chunks.contains_key(&pos) &&  !chunks_connected.contains(&pos) {
-                let start = (chunk.0 * 16 + 8, 0, chunk.1 * 16 + 8);
-                let end = (pos.0 * 16 + 8, 0, pos.1 * 16 + 8);
-                region.fill(start.into(), end.into(), &concrete);
-                region.set_block_state((chunk.0 * 16 + 8, 1, chunk.1 * 16 + 15).into(), &chest);
-                chunks_connected.insert(pos);
-                chunks_to_explore.insert(pos);
-            }
-            let pos = (chunk.0, chunk.1 - 1);
-            if chunks.contains_key(&pos) &&  !chunks_connected.contains(&pos) {
-                let start = (chunk.0 * 16 + 8, 0, chunk.1 * 16 + 8);
-                let end = (pos.0 * 16 + 8, 0, pos.1 * 16 + 8);
-                region.fill(start.into(), end.into(), &concrete);
-                region.set_block_state((chunk.0 * 16 + 8, 1, chunk.1 * 16 + 0).into(), &chest);
-                chunks_connected.insert(pos);
-                chunks_to_explore.insert(pos);
-            }
+    // Blocks are placed relative to the paste origin, the north-west corner of the anchor chunk
+    let anchor = options.anchor_chunk.unwrap_or_else(|| corridors.root());
+    let origin = (anchor.0 * 16, options.paste_y, anchor.1 * 16);
+    let local = |x: i32, y: i32, z: i32| BlockPos::new(x - origin.0, y, z - origin.2);
+
+    // Generate the blocks of each segment on the worker threads, then place them in segment order
+    let segment_blocks = parallel::map(corridors.segments(), |segment| {
+        let (start, step) = (chunk_center(segment.from), segment.step());
+        let floor: Vec<_> = (0 ..= segment.len() * 16)
+            .map(|i| local(start.0 + step.0 * i, 0, start.1 + step.1 * i))
+            .collect();
+        let markers: Vec<_> = segment.markers().map(|marker| local(marker.0, 1, marker.1)).collect();
+        (floor, markers)
+    });
+    for (floor, markers) in segment_blocks {
+        for pos in floor {
+            region.set_block_state(pos, &concrete);
+        }
+        for pos in markers {
+            region.set_block_state(pos, &chest);
         }
     }
 
+    let block_count = region.block_count();
+    println!("Total blocks placed: {}", block_count);
+    println!("Paste origin: {} {} {} (chunk {}, {})", origin.0, origin.1, origin.2, anchor.0, anchor.1);
+
+    let description = format!("Paste at {} {} {}", origin.0, origin.1, origin.2);
+    let bounds = region.bounds();
+
     let path = Path::new("out/chunks.litematic");
     let file = File::create(path)?;
     let mut buffer = BufWriter::new(file);
     let mut schematic = Schematic::new();
     schematic.set_name("ChunkGrid");
+    schematic.set_description(&description);
     schematic.add_region(region);
     schematic.write_to(&mut buffer)?;
 
+    let path = Path::new("out/chunks.construction");
+    let file = File::create(path)?;
+    let mut buffer = BufWriter::new(file);
+    schematic.write_construction_to(&mut buffer)?;
+
+    println!("Generating report...");
+    let mut file = BufWriter::new(File::create(Path::new("out/report.txt"))?);
+    writeln!(file, "Paste origin: {} {} {}", origin.0, origin.1, origin.2)?;
+    writeln!(file, "Anchor chunk: {} {}", anchor.0, anchor.1)?;
+    if let Some((min, max)) = bounds {
+        writeln!(file, "Schematic bounds: {} {} {} to {} {} {}",
+            min.x() + origin.0, min.y() + origin.1, min.z() + origin.2,
+            max.x() + origin.0, max.y() + origin.1, max.z() + origin.2)?;
+    }
+    writeln!(file, "Corridor chunks: {}", corridors.chunks().len())?;
+    writeln!(file, "Blocks: {}", block_count)?;
+    writeln!(file)?;
+    writeln!(file, "Target chunks (chunk x z, block x z of north-west corner):")?;
+    for target in targets.iter().filter(|target| !spares.contains(target)) {
+        writeln!(file, "{} {} {} {}", target.0, target.1, target.0 * 16, target.1 * 16)?;
+    }
+    if !spares.is_empty() {
+        writeln!(file)?;
+        writeln!(file, "Spare chunks (chunk x z, block x z of north-west corner):")?;
+        for spare in targets.iter().filter(|target| spares.contains(target)) {
+            writeln!(file, "{} {} {} {}", spare.0, spare.1, spare.0 * 16, spare.1 * 16)?;
+        }
+    }
+    if !footprint.chunks().is_empty() {
+        writeln!(file)?;
+        writeln!(file, "Footprint chunks: {}", footprint.chunks().len())?;
+        writeln!(file, "Footprint chunks with a cluster hash (chunk x z hash, * if selected):")?;
+        let mut chunks: Vec<_> = footprint.chunks().iter().copied().collect();
+        chunks.sort_unstable();
+        for chunk in chunks {
+            let hash = hash_at(chunk.0, chunk.1);
+            let selected = footprint_members.contains(&chunk);
+            if hash >= min_hash || selected {
+                writeln!(file, "{} {} {}{}", chunk.0, chunk.1, hash, if selected { " *" } else { "" })?;
+            }
+        }
+    }
+    if !corridors.unreachable().is_empty() {
+        writeln!(file)?;
+        writeln!(file, "Targets the corridors can't reach (chunk x z):")?;
+        for target in corridors.unreachable() {
+            writeln!(file, "{} {}", target.0, target.1)?;
+        }
+    }
+    if !corridors.skipped().is_empty() {
+        writeln!(file)?;
+        writeln!(file, "Spare chunks left out to stay within {} blocks (chunk x z):", max_blocks)?;
+        for spare in corridors.skipped() {
+            writeln!(file, "{} {}", spare.0, spare.1)?;
+        }
+    }
+
+    println!("Generating build route...");
+    let commands = route::commands(&corridors, options.dimension.as_deref());
+    let mut file = BufWriter::new(File::create(Path::new("out/route.txt"))?);
+    for command in &commands {
+        writeln!(file, "{}", command)?;
+    }
+
+    if corridors.block_count() > max_blocks {
+        return Err(format!(
+            "No plan found within {} blocks, the best plan needs {} blocks. It has been written to out/ regardless.\n\
+            A larger --view-distance lets the corridors skip more chunks.",
+            max_blocks, corridors.block_count()
+        ).into());
+    }
+
+    if let Some((address, password)) = &options.rcon {
+        println!("Building on {} over RCON...", address);
+        let mut rcon = Rcon::connect(address, password)?;
+        let mut log = BufWriter::new(File::create(Path::new("out/rcon.log"))?);
+
+        let dimension = options.dimension.as_deref();
+        let targets: Vec<_> = targets.iter().copied().filter(|target| !corridors.skipped().contains(target)).collect();
+        let chunks = route::forced_chunks(&corridors, &targets);
+        let mut failed = Vec::new();
+
+        // Blocks can only be placed once the force loaded chunks are actually loaded
+        for command in route::forceload_commands(&chunks, dimension) {
+            let response = rcon.command(&command)?;
+            if rcon::is_error(&response) {
+                failed.push(command.clone());
+            }
+            writeln!(log, "> {}\n{}", command, response)?;
+        }
+        println!("Waiting for {} force loaded chunks to load...", chunks.len());
+        let unloaded = wait_until_loaded(&mut rcon, &chunks, options.paste_y, dimension)?;
+        if !unloaded.is_empty() {
+            writeln!(log)?;
+            writeln!(log, "Chunks not loaded after {} seconds (chunk x z):", LOAD_TIMEOUT.as_secs())?;
+            for chunk in &unloaded {
+                writeln!(log, "{} {}", chunk.0, chunk.1)?;
+            }
+            log.flush()?;
+            return Err(format!(
+                "{} chunks didn't load within {} seconds, nothing was built, see out/rcon.log",
+                unloaded.len(), LOAD_TIMEOUT.as_secs()
+            ).into());
+        }
+
+        let commands = route::build_commands(&corridors, options.paste_y, dimension, FLOOR_BLOCK, MARKER_BLOCK);
+        for command in &commands {
+            let response = rcon.command(command)?;
+            if rcon::is_error(&response) {
+                failed.push(command.clone());
+            }
+            writeln!(log, "> {}\n{}", command, response)?;
+        }
+        println!("Sent {} commands, {} failed", chunks.len() + commands.len(), failed.len());
+
+        // Read back every floor block and marker from the server
+        let checks = route::check_commands(&corridors, options.paste_y, dimension, FLOOR_BLOCK, MARKER_BLOCK);
+        let mut missing = Vec::new();
+        for command in &checks {
+            let response = rcon.command(command)?;
+            if !response.contains("Test passed") {
+                missing.push(command.clone());
+            }
+            writeln!(log, "> {}\n{}", command, response)?;
+        }
+        println!("Verified {} of {} blocks on the server", checks.len() - missing.len(), checks.len());
+
+        // Every target has to stay loaded for the cluster to work
+        let mut target_states = Vec::new();
+        for &target in &targets {
+            let forced = route::forced_command(target, dimension);
+            let forced_response = rcon.command(&forced)?;
+            let loaded = route::loaded_command(target, options.paste_y, dimension);
+            let loaded_response = rcon.command(&loaded)?;
+            writeln!(log, "> {}\n{}\n> {}\n{}", forced, forced_response, loaded, loaded_response)?;
+            target_states.push((target, forced_response.contains("is marked"), loaded_response.contains("Test passed")));
+        }
+        let unforced = target_states.iter().filter(|state| !state.1).count();
+        let unloaded = target_states.iter().filter(|state| !state.2).count();
+        println!(
+            "{} of {} target chunks are force loaded, {} are loaded",
+            targets.len() - unforced, targets.len(), targets.len() - unloaded
+        );
+
+        writeln!(log)?;
+        writeln!(log, "Target chunks (chunk x z, force loaded, loaded):")?;
+        for ((x, z), forced, loaded) in &target_states {
+            writeln!(log, "{} {} {} {}", x, z, forced, loaded)?;
+        }
+
+        if !failed.is_empty() || !missing.is_empty() || unforced > 0 || unloaded > 0 {
+            for (title, commands) in [("Failed commands:", &failed), ("Blocks not found on the server:", &missing)] {
+                if !commands.is_empty() {
+                    writeln!(log)?;
+                    writeln!(log, "{}", title)?;
+                    for command in commands {
+                        writeln!(log, "{}", command)?;
+                    }
+                }
+            }
+            log.flush()?;
+            return Err(format!(
+                "{} commands failed, {} blocks are missing and {} target chunks aren't force loaded \
+                or loaded on the server, see out/rcon.log",
+                failed.len(), missing.len(), target_states.iter().filter(|state| !state.1 || !state.2).count()
+            ).into());
+        }
+    }
+
     println!("Done!");
 
     Ok(())
@@ -0,0 +1,200 @@
+use std::time::{Duration, Instant};
+
+use crate::Chunk;
+
+/// Small deterministic xorshift generator, good enough for picking moves.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Spread of a cluster: the sum of squared distances of its chunks to their centroid.
+/// Kept as running sums so swapping a chunk updates it in constant time.
+#[derive(Copy, Clone)]
+struct Spread {
+    count: f64,
+    sum_x: f64,
+    sum_z: f64,
+    sum_squares: f64
+}
+
+impl Spread {
+    fn new(chunks: &[Chunk]) -> Spread {
+        let mut spread = Spread { count: chunks.len() as f64, sum_x: 0.0, sum_z: 0.0, sum_squares: 0.0 };
+        for chunk in chunks {
+            spread.add(chunk, 1.0);
+        }
+        spread
+    }
+
+    fn add(&mut self, chunk: &Chunk, sign: f64) {
+        let (x, z) = (chunk.x as f64, chunk.z as f64);
+        self.sum_x += sign * x;
+        self.sum_z += sign * z;
+        self.sum_squares += sign * (x * x + z * z);
+    }
+
+    fn swapped(mut self, out: &Chunk, into: &Chunk) -> Spread {
+        self.add(out, -1.0);
+        self.add(into, 1.0);
+        self
+    }
+
+    fn cost(&self) -> f64 {
+        self.sum_squares - (self.sum_x * self.sum_x + self.sum_z * self.sum_z) / self.count
+    }
+}
+
+/// Searches `candidates` for a more compact cluster than `initial` by simulated annealing,
+/// for at most `time_limit`. Chunks are swapped in and out of the cluster as long as it stays
+/// valid: sorted by hash, the `k`-th chunk must have a hash of at most `min_hash + k`.
+/// `initial` must be a valid cluster of candidates. Returns the most compact cluster found.
+pub fn anneal(candidates: &[Chunk], initial: &[Chunk], min_hash: u64, time_limit: Duration) -> Vec<Chunk> {
+    let size = initial.len();
+    if size == 0 || candidates.len() <= size {
+        return initial.to_vec();
+    }
+
+    let mut selected: Vec<usize> = initial.iter()
+        .map(|chunk| candidates.iter().position(|c| c == chunk).expect("initial chunk is no candidate"))
+        .collect();
+    let mut is_selected = vec![false; candidates.len()];
+    for &index in &selected {
+        is_selected[index] = true;
+    }
+    let mut unselected: Vec<usize> = (0 .. candidates.len()).filter(|&i| !is_selected[i]).collect();
+
+    // slack[t]: how many more chunks than needed have a hash of at most min_hash + t
+    let offset = |chunk: &Chunk| ((chunk.hash - min_hash) as usize).min(size);
+    let mut slack = vec![0i64; size + 1];
+    for &index in &selected {
+        slack[offset(&candidates[index])] += 1;
+    }
+    for t in 0 ..= size {
+        slack[t] += if t > 0 { slack[t - 1] } else { 0 };
+    }
+    for (t, value) in slack.iter_mut().enumerate() {
+        *value -= (t + 1).min(size) as i64;
+    }
+
+    let chunks = |selected: &[usize]| selected.iter().map(|&i| candidates[i]).collect::<Vec<_>>();
+    let mut spread = Spread::new(&chunks(&selected));
+    let mut best = (spread.cost(), selected.clone());
+
+    let mut random = Random(0x9E3779B97F4A7C15);
+    let start = Instant::now();
+    let initial_temperature = spread.cost() / size as f64;
+    let mut temperature = initial_temperature;
+
+    for iteration in 0u64 .. {
+        if iteration % 1024 == 0 {
+            let progress = start.elapsed().as_secs_f64() / time_limit.as_secs_f64().max(f64::EPSILON);
+            if progress >= 1.0 {
+                break;
+            }
+            temperature = initial_temperature * (-8.0 * progress).exp();
+        }
+
+        let (out_slot, in_slot) = (random.below(size), random.below(unselected.len()));
+        let (out, into) = (&candidates[selected[out_slot]], &candidates[unselected[in_slot]]);
+
+        // Swapping in a higher hash leaves one chunk less below the hashes in between
+        let (out_offset, in_offset) = (offset(out), offset(into));
+        if in_offset > out_offset && slack[out_offset .. in_offset].iter().any(|&s| s < 1) {
+            continue;
+        }
+
+        let swapped = spread.swapped(out, into);
+        let delta = swapped.cost() - spread.cost();
+        if delta > 0.0 && random.unit() >= (-delta / temperature).exp() {
+            continue;
+        }
+
+        if in_offset > out_offset {
+            slack[out_offset .. in_offset].iter_mut().for_each(|s| *s -= 1);
+        } else {
+            slack[in_offset .. out_offset].iter_mut().for_each(|s| *s += 1);
+        }
+        std::mem::swap(&mut selected[out_slot], &mut unselected[in_slot]);
+        spread = swapped;
+
+        if spread.cost() < best.0 {
+            best = (spread.cost(), selected.clone());
+        }
+    }
+
+    chunks(&best.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mix;
+
+    const MIN_HASH: u64 = 200;
+    const SIZE: usize = 60;
+
+    /// Candidates on a grid with pseudo random hashes, and a valid cluster
+    /// taking the lowest sufficient hashes first.
+    fn cluster() -> (Vec<Chunk>, Vec<Chunk>) {
+        let mut candidates = Vec::new();
+        for x in 0 .. 20 {
+            for z in 0 .. 20 {
+                let hash = mix(x * 20 + z) % (MIN_HASH + SIZE as u64);
+                if hash >= MIN_HASH {
+                    candidates.push(Chunk { x: x as i32, z: z as i32, hash });
+                }
+            }
+        }
+
+        let mut sorted = candidates.clone();
+        sorted.sort_by_key(|chunk| chunk.hash);
+        let mut initial = Vec::new();
+        for chunk in sorted {
+            if initial.len() < SIZE && chunk.hash <= MIN_HASH + initial.len() as u64 {
+                initial.push(chunk);
+            }
+        }
+        assert_eq!(initial.len(), SIZE);
+
+        (candidates, initial)
+    }
+
+    fn is_valid(cluster: &[Chunk]) -> bool {
+        let mut hashes: Vec<_> = cluster.iter().map(|chunk| chunk.hash).collect();
+        hashes.sort_unstable();
+        hashes.iter().enumerate().all(|(k, &hash)| hash >= MIN_HASH && hash <= MIN_HASH + k as u64)
+    }
+
+    #[test]
+    fn annealed_cluster_stays_valid() {
+        let (candidates, initial) = cluster();
+        assert!(is_valid(&initial));
+
+        let result = anneal(&candidates, &initial, MIN_HASH, Duration::from_millis(200));
+        assert_eq!(result.len(), SIZE);
+        assert!(result.iter().all(|chunk| candidates.contains(chunk)));
+
+        let mut distinct = result.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), SIZE);
+
+        assert!(is_valid(&result));
+        assert!(Spread::new(&result).cost() <= Spread::new(&initial).cost());
+    }
+}
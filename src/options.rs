@@ -0,0 +1,97 @@
+use std::str::FromStr;
+
+const USAGE: &str = "Usage: easy-cluster [--view-distance N] [--dimension ID] [--anchor-chunk X,Z] [--paste-y Y] [--max-blocks N]\n\
+    [--footprint PATH] [--footprint-origin X,Z] [--tolerance FRACTION]\n\
+    [--rcon HOST:PORT:PASSWORD] [--optimize SECONDS]";
+
+/// Command line options.
+#[derive(Default, Debug)]
+pub struct Options {
+    /// Simulation distance of a player travelling along the corridor.
+    /// If set, corridors only need to pass within this distance of each target.
+    pub view_distance: Option<i32>,
+    /// Dimension the build route teleports in, e.g. `minecraft:the_nether`.
+    pub dimension: Option<String>,
+    /// Chunk whose north-west corner is used as the schematic paste origin.
+    /// Defaults to the corridor root chunk.
+    pub anchor_chunk: Option<(i32, i32)>,
+    /// World height the schematic is meant to be pasted at.
+    pub paste_y: i32,
+    /// Maximum number of blocks the corridors may take to build.
+    /// Spare chunks are left out as needed to stay within it.
+    pub max_blocks: Option<usize>,
+    /// Existing structure the cluster is anchored to, see `Footprint::load`.
+    pub footprint: Option<String>,
+    /// Block position the regions of a Litematica footprint are relative to.
+    pub footprint_origin: (i32, i32),
+    /// Fraction of the cluster size to select as spare chunks, with hashes just below the required ones.
+    pub tolerance: f64,
+    /// Address and password of a test server to build the corridors on.
+    pub rcon: Option<(String, String)>,
+    /// Time limit for searching a more compact cluster than the greedy selection.
+    pub optimize: Option<f64>
+}
+
+impl Options {
+    pub fn from_args() -> Result<Options, String> {
+        Options::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut options = Options::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--view-distance" => options.view_distance = Some(value(&arg, &mut args)?),
+                "--dimension" => options.dimension = Some(value(&arg, &mut args)?),
+                "--anchor-chunk" => options.anchor_chunk = Some(pair(&arg, &mut args)?),
+                "--paste-y" => options.paste_y = value(&arg, &mut args)?,
+                "--max-blocks" => options.max_blocks = Some(value(&arg, &mut args)?),
+                "--footprint" => options.footprint = Some(value(&arg, &mut args)?),
+                "--footprint-origin" => options.footprint_origin = pair(&arg, &mut args)?,
+                "--tolerance" => options.tolerance = value(&arg, &mut args)?,
+                "--rcon" => options.rcon = Some(rcon(&arg, &mut args)?),
+                "--optimize" => options.optimize = Some(value(&arg, &mut args)?),
+                "-h" | "--help" => return Err(USAGE.to_string()),
+                _ => return Err(format!("Unknown argument '{}'\n{}", arg, USAGE))
+            }
+        }
+
+        if options.view_distance.is_some_and(|d| d < 0) {
+            return Err("View distance must not be negative".to_string());
+        }
+        if options.tolerance < 0.0 || !options.tolerance.is_finite() {
+            return Err("Tolerance must be a non-negative fraction".to_string());
+        }
+        if options.optimize.is_some_and(|seconds| seconds <= 0.0 || !seconds.is_finite()) {
+            return Err("Optimization time limit must be a positive number of seconds".to_string());
+        }
+
+        Ok(options)
+    }
+}
+
+fn value<T: FromStr, I: Iterator<Item = String>>(flag: &str, args: &mut I) -> Result<T, String> {
+    let value = args.next().ok_or_else(|| format!("Missing value for {}", flag))?;
+    value.parse().map_err(|_| format!("Invalid value '{}' for {}", value, flag))
+}
+
+fn pair<I: Iterator<Item = String>>(flag: &str, args: &mut I) -> Result<(i32, i32), String> {
+    let value: String = value(flag, args)?;
+    let mut parts = value.split(',').map(|part| part.trim().parse::<i32>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(x)), Some(Ok(z)), None) => Ok((x, z)),
+        _ => Err(format!("Invalid value '{}' for {}, expected X,Z", value, flag))
+    }
+}
+
+fn rcon<I: Iterator<Item = String>>(flag: &str, args: &mut I) -> Result<(String, String), String> {
+    let value: String = value(flag, args)?;
+    let mut parts = value.splitn(3, ':');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(host), Some(port), Some(password)) if port.parse::<u16>().is_ok() => {
+            Ok((format!("{}:{}", host, port), password.to_string()))
+        },
+        _ => Err(format!("Invalid value for {}, expected HOST:PORT:PASSWORD", flag))
+    }
+}
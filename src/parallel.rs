@@ -0,0 +1,56 @@
+use std::{sync::OnceLock, thread};
+
+/// Number of worker threads to split work across.
+/// Looked up once, as it reads the CPU limits of the process.
+pub fn threads() -> usize {
+    static THREADS: OnceLock<usize> = OnceLock::new();
+    *THREADS.get_or_init(|| thread::available_parallelism().map(|n| n.get()).unwrap_or(1))
+}
+
+/// Length of the parts to split `len` items into, one per worker thread but at least `min_len` long.
+fn part_len(len: usize, min_len: usize) -> usize {
+    len.div_ceil(threads()).max(min_len).max(1)
+}
+
+/// Runs `f` on consecutive parts of `items`, one per worker thread but each at least `min_len`
+/// items long, passing the index of the first item of the part. The results are in the order of the parts.
+pub fn map_parts<T: Sync, R: Send, F: Fn(usize, &[T]) -> R + Sync>(items: &[T], min_len: usize, f: F) -> Vec<R> {
+    let part_len = part_len(items.len(), min_len);
+    if part_len >= items.len() {
+        return vec![f(0, items)];
+    }
+
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = items.chunks(part_len)
+            .enumerate()
+            .map(|(index, part)| scope.spawn(move || f(index * part_len, part)))
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+/// Like `map_parts`, with mutable access to the items.
+pub fn map_parts_mut<T: Send, R: Send, F: Fn(usize, &mut [T]) -> R + Sync>(items: &mut [T], min_len: usize, f: F) -> Vec<R> {
+    let part_len = part_len(items.len(), min_len);
+    if part_len >= items.len() {
+        return vec![f(0, items)];
+    }
+
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = items.chunks_mut(part_len)
+            .enumerate()
+            .map(|(index, part)| scope.spawn(move || f(index * part_len, part)))
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+/// Maps `items` on all worker threads. The results are in the order of `items`.
+pub fn map<T: Sync, R: Send, F: Fn(&T) -> R + Sync>(items: &[T], f: F) -> Vec<R> {
+    map_parts(items, 1, |_, part| part.iter().map(&f).collect::<Vec<_>>())
+        .into_iter()
+        .flatten()
+        .collect()
+}
@@ -0,0 +1,106 @@
+use std::{io::{Error, ErrorKind, Read, Write}, net::{TcpStream, ToSocketAddrs}, time::Duration};
+
+const LOGIN: i32 = 3;
+const COMMAND: i32 = 2;
+
+/// Maximum packet length a server sends or accepts.
+const MAX_PACKET_LENGTH: i32 = 4110;
+
+/// How long to wait for the server before giving up.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Parts of the vanilla server's messages for commands that failed to run.
+const ERRORS: &[&str] = &[
+    "Unknown", "Incorrect argument", "Expected", "Invalid", "<--[HERE]",
+    "not loaded", "out of this world", "Too many", "unexpected error"
+];
+
+/// Whether a command response reports an error.
+pub fn is_error(response: &str) -> bool {
+    ERRORS.iter().any(|error| response.contains(error))
+}
+
+/// Replaces the errors of a read or write timing out with a clearer one.
+fn timed_out(error: Error) -> Error {
+    match error.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => Error::new(ErrorKind::TimedOut, "RCON server didn't respond in time"),
+        _ => error
+    }
+}
+
+/// A client for the remote console of a Minecraft server.
+pub struct Rcon {
+    stream: TcpStream,
+    next_id: i32
+}
+
+impl Rcon {
+    /// Connects to `address` and logs in with `password`.
+    pub fn connect(address: &str, password: &str) -> Result<Rcon, Error> {
+        let address = address.to_socket_addrs()?
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "RCON address not found"))?;
+        let stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let mut rcon = Rcon {
+            stream,
+            next_id: 1
+        };
+
+        let id = rcon.send(LOGIN, password)?;
+        let (response_id, _) = rcon.receive()?;
+        if response_id != id {
+            return Err(Error::new(ErrorKind::PermissionDenied, "RCON login failed, check the password"));
+        }
+
+        Ok(rcon)
+    }
+
+    /// Runs a command on the server, returning its response.
+    pub fn command(&mut self, command: &str) -> Result<String, Error> {
+        let id = self.send(COMMAND, command)?;
+        let (response_id, body) = self.receive()?;
+        if response_id != id {
+            return Err(Error::new(ErrorKind::InvalidData, "RCON response doesn't match the command"));
+        }
+        Ok(body)
+    }
+
+    fn send(&mut self, kind: i32, body: &str) -> Result<i32, Error> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let length = 4 + 4 + body.len() as i32 + 2;
+        if length > MAX_PACKET_LENGTH {
+            return Err(Error::new(ErrorKind::InvalidInput, "RCON command too long"));
+        }
+
+        let mut packet = Vec::with_capacity(length as usize + 4);
+        packet.extend_from_slice(&length.to_le_bytes());
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.extend_from_slice(&kind.to_le_bytes());
+        packet.extend_from_slice(body.as_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        self.stream.write_all(&packet).map_err(timed_out)?;
+
+        Ok(id)
+    }
+
+    fn receive(&mut self) -> Result<(i32, String), Error> {
+        let mut int = [0; 4];
+        self.stream.read_exact(&mut int).map_err(timed_out)?;
+        let length = i32::from_le_bytes(int);
+        if !(10 ..= MAX_PACKET_LENGTH).contains(&length) {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid RCON packet length"));
+        }
+
+        let mut packet = vec![0; length as usize];
+        self.stream.read_exact(&mut packet).map_err(timed_out)?;
+        let id = i32::from_le_bytes([packet[0], packet[1], packet[2], packet[3]]);
+        let body = String::from_utf8_lossy(&packet[8 .. packet.len() - 2]).into_owned();
+
+        Ok((id, body))
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use crate::corridor::{Corridors, chunk_center};
+
+/// Orders the corridor segments for building, walking the corridor tree depth first
+/// from the root. After finishing a segment, the branches closest to its end are
+/// built first, so the route only backtracks along already built corridors.
+pub fn build_order(corridors: &Corridors) -> Vec<usize> {
+    let segments = corridors.segments();
+
+    let mut branches: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+    for (index, segment) in segments.iter().enumerate() {
+        branches.entry(segment.from).or_default().push(index);
+    }
+
+    let mut order = Vec::with_capacity(segments.len());
+    let mut visited = vec![false; segments.len()];
+    let mut stack: Vec<usize> = branches.get(&corridors.root()).cloned().unwrap_or_default();
+    stack.reverse();
+
+    while let Some(index) = stack.pop() {
+        if visited[index] {
+            continue;
+        }
+        visited[index] = true;
+        order.push(index);
+
+        for chunk in segments[index].chunks().skip(1) {
+            if let Some(children) = branches.get(&chunk) {
+                stack.extend(children.iter().filter(|&&child| !visited[child]));
+            }
+        }
+    }
+
+    order
+}
+
+/// Wraps `command` in `execute in` if a dimension is given.
+pub fn in_dimension(dimension: Option<&str>, command: String) -> String {
+    match dimension {
+        Some(dimension) => format!("execute in {} run {}", dimension, command),
+        None => command
+    }
+}
+
+/// Teleport commands visiting every segment and marker in build order, meant to be
+/// run one at a time while building. Positions repeating the previous one are skipped.
+/// If a dimension is given, the commands are wrapped in `execute in`.
+pub fn commands(corridors: &Corridors, dimension: Option<&str>) -> Vec<String> {
+    let segments = corridors.segments();
+    let mut commands = Vec::new();
+    let mut last = None;
+
+    for (number, index) in build_order(corridors).into_iter().enumerate() {
+        let segment = &segments[index];
+        commands.push(format!(
+            "# Segment {}: chunk {:?} to chunk {:?}, {} markers",
+            number + 1, segment.from, segment.to, segment.len()
+        ));
+
+        let positions = std::iter::once(chunk_center(segment.from))
+            .chain(segment.markers())
+            .chain(std::iter::once(chunk_center(segment.to)));
+        for (x, z) in positions {
+            if last != Some((x, z)) {
+                commands.push(in_dimension(dimension, format!("tp @s {} ~ {}", x, z)));
+                last = Some((x, z));
+            }
+        }
+    }
+
+    commands
+}
+
+/// Commands testing that every floor block and marker of the corridors is in place.
+/// Each responds with `Test passed` if the block is there.
+pub fn check_commands(corridors: &Corridors, y: i32, dimension: Option<&str>, floor: &str, marker: &str) -> Vec<String> {
+    let check = |(x, z): (i32, i32), y: i32, block: &str| in_dimension(dimension, format!("execute if block {} {} {} {}", x, y, z, block));
+
+    let mut commands = Vec::new();
+    for segment in corridors.segments() {
+        commands.push(check(chunk_center(segment.from), y, floor));
+        for position in segment.markers() {
+            commands.push(check(position, y, floor));
+            commands.push(check(position, y + 1, marker));
+        }
+        commands.push(check(chunk_center(segment.to), y, floor));
+    }
+
+    commands
+}
+
+/// The targets and corridor chunks, which have to be force loaded before building.
+pub fn forced_chunks(corridors: &Corridors, targets: &[(i32, i32)]) -> Vec<(i32, i32)> {
+    let mut chunks: Vec<_> = targets.iter().chain(corridors.chunks().keys()).copied().collect();
+    chunks.sort_unstable();
+    chunks.dedup();
+    chunks
+}
+
+/// Commands force loading `chunks`.
+pub fn forceload_commands(chunks: &[(i32, i32)], dimension: Option<&str>) -> Vec<String> {
+    chunks.iter()
+        .map(|chunk| in_dimension(dimension, format!("forceload add {} {}", chunk.0 * 16, chunk.1 * 16)))
+        .collect()
+}
+
+/// Command asking whether `chunk` is force loaded.
+/// Responds with `... is marked for force loading` if it is.
+pub fn forced_command(chunk: (i32, i32), dimension: Option<&str>) -> String {
+    in_dimension(dimension, format!("forceload query {} {}", chunk.0 * 16, chunk.1 * 16))
+}
+
+/// Command testing that `chunk` is loaded. Responds with `Test passed` if it is.
+/// Force loaded chunks only load on the following ticks.
+pub fn loaded_command(chunk: (i32, i32), y: i32, dimension: Option<&str>) -> String {
+    let (x, z) = chunk_center(chunk);
+    in_dimension(dimension, format!("execute if loaded {} {} {}", x, y, z))
+}
+
+/// Commands building the corridors directly in the world at height `y`.
+/// The chunks from `forced_chunks` must be loaded, so every block can be placed.
+pub fn build_commands(corridors: &Corridors, y: i32, dimension: Option<&str>, floor: &str, marker: &str) -> Vec<String> {
+    let mut commands = Vec::new();
+    let segments = corridors.segments();
+    for index in build_order(corridors) {
+        let segment = &segments[index];
+        let (start, end) = (chunk_center(segment.from), chunk_center(segment.to));
+        commands.push(in_dimension(dimension, format!(
+            "fill {} {} {} {} {} {} {}", start.0, y, start.1, end.0, y, end.1, floor
+        )));
+        for (x, z) in segment.markers() {
+            commands.push(in_dimension(dimension, format!("setblock {} {} {} {}", x, y + 1, z, marker)));
+        }
+    }
+
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn corridors() -> Corridors {
+        let targets = [(3, 0), (3, 4), (-2, 0), (-2, -5), (6, 2), (0, 7)];
+        Corridors::plan((0, 0), &targets, &[], &HashSet::new(), 0, usize::MAX, false)
+    }
+
+    #[test]
+    fn segments_are_built_once_after_their_start() {
+        let corridors = corridors();
+        let segments = corridors.segments();
+        let order = build_order(&corridors);
+
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0 .. segments.len()).collect::<Vec<_>>());
+
+        let mut built = HashSet::new();
+        built.insert(corridors.root());
+        for index in order {
+            assert!(built.contains(&segments[index].from), "{:?} is built before its start", segments[index]);
+            built.extend(segments[index].chunks());
+        }
+    }
+
+    #[test]
+    fn repeated_teleports_are_skipped() {
+        let corridors = Corridors::plan((0, 0), &[(3, 0), (3, 3)], &[], &HashSet::new(), 0, usize::MAX, false);
+        assert_eq!(corridors.segments().len(), 2);
+
+        let teleports: Vec<_> = commands(&corridors, None).into_iter()
+            .filter(|command| !command.starts_with('#'))
+            .collect();
+        assert!(teleports.windows(2).all(|pair| pair[0] != pair[1]));
+
+        let corner = chunk_center((3, 0));
+        let corner = format!("tp @s {} ~ {}", corner.0, corner.1);
+        assert_eq!(teleports.iter().filter(|&command| *command == corner).count(), 1);
+        // Start, corner and end, and one marker per chunk link
+        assert_eq!(teleports.len(), 3 + 6);
+    }
+
+    #[test]
+    fn targets_off_the_corridors_are_force_loaded() {
+        let targets = [(0, 0), (6, 0), (6, 2)];
+        let corridors = Corridors::plan((0, 0), &targets, &[], &HashSet::new(), 2, usize::MAX, false);
+        assert!(!corridors.chunks().contains_key(&(6, 2)));
+
+        let chunks = forced_chunks(&corridors, &targets);
+        assert!(chunks.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(targets.iter().all(|target| chunks.contains(target)));
+        assert!(corridors.chunks().keys().all(|chunk| chunks.contains(chunk)));
+        assert_eq!(forceload_commands(&chunks, None).len(), chunks.len());
+    }
+}